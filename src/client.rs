@@ -21,10 +21,11 @@
 use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
 use std::io::Read;
+use std::time::Duration;
 
 use hyper::{
     client::{Client as HyperClient, HttpConnector, connect::Connect},
-    header::{AUTHORIZATION, CONTENT_TYPE},
+    header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION, CONTENT_TYPE},
     Body,
 };
 use hyper_tls::{Error as TlsError, HttpsConnector};
@@ -39,6 +40,8 @@ pub struct Client<C> {
     pass: Option<String>,
     client: HyperClient<C, Body>,
     nonce: Arc<Mutex<u64>>,
+    timeout: Option<Duration>,
+    headers: HeaderMap,
 }
 
 impl<C> Client<C>
@@ -49,16 +52,7 @@ where
 {
     /// Creates a new client
     pub fn new(url: String, user: Option<String>, pass: Option<String>) -> Client<HttpConnector> {
-        // Check that if we have a password, we have a username; other way around is ok
-        debug_assert!(pass.is_none() || user.is_some());
-
-        Client {
-            url: url,
-            user: user,
-            pass: pass,
-            client: HyperClient::new(),
-            nonce: Arc::new(Mutex::new(0)),
-        }
+        ClientBuilder::new(url).auth(user, pass).build()
     }
 
     /// Creates a new TLS client
@@ -67,17 +61,7 @@ where
         user: Option<String>,
         pass: Option<String>,
     ) -> Result<Client<HttpsConnector<HttpConnector>>, TlsError> {
-        // Check that if we have a password, we have a username; other way around is ok
-        debug_assert!(pass.is_none() || user.is_some());
-        let https = HttpsConnector::new()?;
-        let https_client = HyperClient::builder().build::<_, Body>(https);
-        Ok(Client {
-            url: url,
-            user: user,
-            pass: pass,
-            client: https_client,
-            nonce: Arc::new(Mutex::new(0)),
-        })
+        ClientBuilder::new(url).auth(user, pass).build_tls()
     }
 
     /// Make a request and deserialize the response
@@ -100,20 +84,18 @@ where
     {
         let json_raw = serde_json::to_vec(body_raw).unwrap(); // This is safe
         let body = Body::from(json_raw);
-        let mut builder = hyper::Request::post(&self.url);
-
-        // Add authorization
-        if let Some(ref user) = self.user {
-            let pass_str = match &self.pass {
-                Some(some) => some,
-                None => "",
-            };
-            builder = builder.header(AUTHORIZATION, format!("Basic {}:{}", user, pass_str))
-        };
+        let builder = hyper::Request::post(&self.url);
+        let builder = apply_headers(builder, &self.user, &self.pass, &self.headers);
+
         let request = builder.body(body).unwrap(); // This is safe
 
-        // Send request
-        let response = self.client.request(request).await?;
+        // Send request, bounding it by the configured timeout if any.
+        let response = match self.timeout {
+            Some(timeout) => tokio::time::timeout(timeout, self.client.request(request))
+                .await
+                .map_err(|_| Error::Timeout)??,
+            None => self.client.request(request).await?,
+        };
         let body = response.into_body().try_concat().await?;
         let parsed: R = serde_json::from_slice(&body)?;
 
@@ -137,14 +119,20 @@ where
     ///
     /// Note that the requests need to have valid IDs, so it is advised to create the requests
     /// with [build_request].
+    ///
+    /// Parses the reply via [`crate::Message`], since some servers collapse
+    /// a one-element batch response down to a bare response object instead
+    /// of a one-element array.
     pub async fn send_batch(&self, requests: &[Request<'_, '_>]) -> Result<Vec<Option<Response>>, Error> {
         if requests.len() < 1 {
             return Err(Error::EmptyBatch);
         }
 
-        // If the request body is invalid JSON, the response is a single response object.
-        // We ignore this case since we are confident we are producing valid JSON.
-        let responses: Vec<Response> = self.send_raw(&requests).await?;
+        let message: crate::Message = self.send_raw(&requests).await?;
+        let responses: Vec<Response> = match message {
+            crate::Message::Batch(responses) => responses,
+            crate::Message::Single(response) => vec![response],
+        };
         if responses.len() > requests.len() {
             return Err(Error::WrongBatchResponseSize);
         }
@@ -156,13 +144,15 @@ where
         // First index responses by ID and catch duplicate IDs.
         let mut resp_by_id = HashMap::new();
         for (id, resp) in ids.iter().zip(responses.into_iter()) {
-            if let Some(dup) = resp_by_id.insert(HashableValue(&id), resp) {
+            if let Some(dup) = resp_by_id.insert(HashableValue::borrowed(&id), resp) {
                 return Err(Error::BatchDuplicateResponseId(dup.id));
             }
         }
         // Match responses to the requests.
-        let results =
-            requests.into_iter().map(|r| resp_by_id.remove(&HashableValue(&r.id))).collect();
+        let results = requests
+            .into_iter()
+            .map(|r| resp_by_id.remove(&HashableValue::borrowed(&r.id)))
+            .collect();
 
         // Since we're also just producing the first duplicate ID, we can also just produce the
         // first incorrect ID in case there are multiple.
@@ -195,6 +185,104 @@ where
     }
 }
 
+/// Sets the `Authorization` header (HTTP Basic, `base64(user:pass)`, not the
+/// cleartext pair) if `user` is set, then merges in `default_headers`.
+/// Pulled out of [`Client::send_raw`] so the header logic can be tested
+/// without a live HTTP server.
+fn apply_headers(
+    mut builder: hyper::http::request::Builder,
+    user: &Option<String>,
+    pass: &Option<String>,
+    default_headers: &HeaderMap,
+) -> hyper::http::request::Builder {
+    if let Some(user) = user {
+        let pass = pass.as_deref().unwrap_or("");
+        let credentials = base64::encode(format!("{}:{}", user, pass));
+        builder = builder.header(AUTHORIZATION, format!("Basic {}", credentials));
+    }
+
+    if let Some(headers) = builder.headers_mut() {
+        for (name, value) in default_headers.iter() {
+            headers.insert(name, value.clone());
+        }
+    }
+
+    builder
+}
+
+/// Builds a [`Client`], configuring request timeouts, default headers, and
+/// basic auth credentials before connecting.
+pub struct ClientBuilder {
+    url: String,
+    user: Option<String>,
+    pass: Option<String>,
+    timeout: Option<Duration>,
+    headers: HeaderMap,
+}
+
+impl ClientBuilder {
+    /// Creates a new builder for a client talking to `url`.
+    pub fn new(url: String) -> Self {
+        ClientBuilder {
+            url,
+            user: None,
+            pass: None,
+            timeout: None,
+            headers: HeaderMap::new(),
+        }
+    }
+
+    /// Sets the HTTP Basic auth credentials.
+    pub fn auth(mut self, user: Option<String>, pass: Option<String>) -> Self {
+        // Check that if we have a password, we have a username; other way around is ok
+        debug_assert!(pass.is_none() || user.is_some());
+        self.user = user;
+        self.pass = pass;
+        self
+    }
+
+    /// Sets the timeout applied to every request.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Adds a default header sent with every request, e.g. `User-Agent` or a
+    /// bearer token for hosted RPC providers.
+    pub fn header(mut self, name: HeaderName, value: HeaderValue) -> Self {
+        self.headers.insert(name, value);
+        self
+    }
+
+    /// Builds a plain HTTP client.
+    pub fn build(self) -> Client<HttpConnector> {
+        Client {
+            url: self.url,
+            user: self.user,
+            pass: self.pass,
+            client: HyperClient::new(),
+            nonce: Arc::new(Mutex::new(0)),
+            timeout: self.timeout,
+            headers: self.headers,
+        }
+    }
+
+    /// Builds a TLS-enabled client.
+    pub fn build_tls(self) -> Result<Client<HttpsConnector<HttpConnector>>, TlsError> {
+        let https = HttpsConnector::new()?;
+        let https_client = HyperClient::builder().build::<_, Body>(https);
+        Ok(Client {
+            url: self.url,
+            user: self.user,
+            pass: self.pass,
+            client: https_client,
+            nonce: Arc::new(Mutex::new(0)),
+            timeout: self.timeout,
+            headers: self.headers,
+        })
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -209,4 +297,49 @@ mod tests {
         assert_eq!(client.last_nonce(), 2);
         assert!(req1 != req2);
     }
+
+    fn header(builder: &hyper::http::request::Builder, name: impl AsRef<str>) -> Option<String> {
+        builder
+            .headers_ref()
+            .and_then(|headers| headers.get(name.as_ref()))
+            .map(|value| value.to_str().unwrap().to_owned())
+    }
+
+    #[test]
+    fn apply_headers_sets_basic_auth_from_user_and_pass() {
+        let builder = hyper::Request::get("/");
+        let builder = apply_headers(
+            builder,
+            &Some("alice".to_owned()),
+            &Some("hunter2".to_owned()),
+            &HeaderMap::new(),
+        );
+        let expected = format!("Basic {}", base64::encode("alice:hunter2"));
+        assert_eq!(header(&builder, "authorization"), Some(expected));
+    }
+
+    #[test]
+    fn apply_headers_defaults_password_to_empty_string() {
+        let builder = hyper::Request::get("/");
+        let builder = apply_headers(builder, &Some("alice".to_owned()), &None, &HeaderMap::new());
+        let expected = format!("Basic {}", base64::encode("alice:"));
+        assert_eq!(header(&builder, "authorization"), Some(expected));
+    }
+
+    #[test]
+    fn apply_headers_omits_auth_when_no_user() {
+        let builder = hyper::Request::get("/");
+        let builder = apply_headers(builder, &None, &None, &HeaderMap::new());
+        assert_eq!(header(&builder, "authorization"), None);
+    }
+
+    #[test]
+    fn apply_headers_merges_default_headers() {
+        let mut defaults = HeaderMap::new();
+        defaults.insert("x-api-key", HeaderValue::from_static("secret"));
+
+        let builder = hyper::Request::get("/");
+        let builder = apply_headers(builder, &None, &None, &defaults);
+        assert_eq!(header(&builder, "x-api-key"), Some("secret".to_owned()));
+    }
 }
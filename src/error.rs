@@ -0,0 +1,280 @@
+// Rust JSON-RPC Library
+// Written in 2015 by
+//     Andrew Poelstra <apoelstra@wpsoftware.net>
+//
+// Forked in 2019 by
+//   Harry Barber <harrybarber@protonmail.com>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the CC0 Public Domain Dedication
+// along with this software.
+// If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
+//
+
+//! # Error handling
+//!
+//! Support for error handling for both transport level errors and
+//! JSONRPC level errors.
+//!
+
+use std::fmt;
+
+#[derive(Debug)]
+/// A library error
+pub enum Error {
+    /// Json error
+    Json(serde_json::Error),
+    /// Hyper error
+    Hyper(hyper::Error),
+    /// WebSocket transport error
+    Ws(tokio_tungstenite::tungstenite::Error),
+    /// I/O error on a transport backed by a raw byte stream (IPC)
+    Io(std::io::Error),
+    /// A request did not complete within the client's configured timeout
+    Timeout,
+    /// The connection was closed, either by the peer or because it went
+    /// quiet for longer than the configured [`crate::ws::PingConfig`]
+    /// allows
+    ConnectionClosed,
+    /// Error response
+    Rpc(RpcError),
+    /// Response to a request did not have the expected nonce
+    NonceMismatch,
+    /// Response to a request had a jsonrpc field other than "2.0"
+    VersionMismatch,
+    /// Batch response had more objects than the batch request
+    WrongBatchResponseSize,
+    /// Batch response contained a duplicate ID
+    BatchDuplicateResponseId(serde_json::Value),
+    /// Batch response contained an ID that didn't correspond to any request ID
+    WrongBatchResponseId(serde_json::Value),
+    /// A batch was sent with no requests in it
+    EmptyBatch,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Json(ref e) => write!(f, "JSON decode error: {}", e),
+            Error::Hyper(ref e) => write!(f, "HTTP transport error: {}", e),
+            Error::Ws(ref e) => write!(f, "WebSocket transport error: {}", e),
+            Error::Io(ref e) => write!(f, "I/O error: {}", e),
+            Error::Timeout => write!(f, "request timed out"),
+            Error::ConnectionClosed => write!(f, "connection closed"),
+            Error::Rpc(ref e) => write!(f, "RPC error response: {}", e),
+            Error::NonceMismatch => write!(f, "Nonce of response did not match nonce of request"),
+            Error::VersionMismatch => write!(f, "`jsonrpc` field set to non-\"2.0\""),
+            Error::WrongBatchResponseSize => {
+                write!(f, "Batch response has more elements than the request had")
+            }
+            Error::BatchDuplicateResponseId(ref v) => {
+                write!(f, "Batch response contained a duplicate ID: {}", v)
+            }
+            Error::WrongBatchResponseId(ref v) => {
+                write!(f, "Batch response contained an unknown ID: {}", v)
+            }
+            Error::EmptyBatch => write!(f, "Batch was empty"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match *self {
+            Error::Json(ref e) => Some(e),
+            Error::Hyper(ref e) => Some(e),
+            Error::Ws(ref e) => Some(e),
+            Error::Io(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Error {
+        Error::Json(e)
+    }
+}
+
+impl From<hyper::Error> for Error {
+    fn from(e: hyper::Error) -> Error {
+        Error::Hyper(e)
+    }
+}
+
+impl From<tokio_tungstenite::tungstenite::Error> for Error {
+    fn from(e: tokio_tungstenite::tungstenite::Error) -> Error {
+        Error::Ws(e)
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Error {
+        Error::Io(e)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+/// A JSONRPC error object
+pub struct RpcError {
+    /// The integer identifier of the error
+    pub code: i32,
+    /// A string describing the error
+    pub message: String,
+    /// Additional data specific to the error
+    pub data: Option<serde_json::Value>,
+}
+
+impl fmt::Display for RpcError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} ({})", self.message, self.code)
+    }
+}
+
+impl RpcError {
+    /// Builds the spec's `-32700 Parse error` response: the request body
+    /// wasn't valid JSON.
+    pub fn parse_error() -> Self {
+        RpcError { code: -32700, message: "Parse error".to_owned(), data: None }
+    }
+
+    /// Builds the spec's `-32600 Invalid Request` response: the JSON was
+    /// valid but wasn't a JSONRPC request object.
+    pub fn invalid_request() -> Self {
+        RpcError { code: -32600, message: "Invalid Request".to_owned(), data: None }
+    }
+
+    /// Builds the spec's `-32601 Method not found` response.
+    pub fn method_not_found() -> Self {
+        RpcError { code: -32601, message: "Method not found".to_owned(), data: None }
+    }
+
+    /// Builds the spec's `-32602 Invalid params` response, for handlers to
+    /// return when the `params` they were given don't match what the method
+    /// expects.
+    pub fn invalid_params() -> Self {
+        RpcError { code: -32602, message: "Invalid params".to_owned(), data: None }
+    }
+
+    /// Builds the spec's `-32603 Internal error` response, for handlers to
+    /// return when they fail for reasons unrelated to the request itself.
+    pub fn internal_error() -> Self {
+        RpcError { code: -32603, message: "Internal error".to_owned(), data: None }
+    }
+
+    /// Deserializes the error's `data` field into `T`, if present.
+    ///
+    /// Returns [`None`] if there is no `data` at all, and `Some(Err(_))` if
+    /// `data` is present but doesn't match the shape of `T`.
+    pub fn data_as<T: serde::de::DeserializeOwned>(&self) -> Option<Result<T, Error>> {
+        self.data
+            .as_ref()
+            .map(|data| T::deserialize(data).map_err(Error::Json))
+    }
+
+    /// Walks the `data` tree (descending into objects and arrays) and
+    /// returns the first string leaf matching `predicate`.
+    ///
+    /// Useful for pulling a specific string (a hex payload, a revert reason)
+    /// out of a provider's deeply-nested, non-standard error `data` without
+    /// hand-writing the traversal at every call site.
+    pub fn find_data_string<P>(&self, predicate: P) -> Option<&str>
+    where
+        P: Fn(&str) -> bool + Copy,
+    {
+        self.data.as_ref().and_then(|data| find_string(data, predicate))
+    }
+}
+
+fn find_string<P>(value: &serde_json::Value, predicate: P) -> Option<&str>
+where
+    P: Fn(&str) -> bool + Copy,
+{
+    match value {
+        serde_json::Value::String(s) => {
+            if predicate(s) {
+                Some(s.as_str())
+            } else {
+                None
+            }
+        }
+        serde_json::Value::Array(values) => {
+            values.iter().find_map(|v| find_string(v, predicate))
+        }
+        serde_json::Value::Object(map) => {
+            map.values().find_map(|v| find_string(v, predicate))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error_with_data(data: serde_json::Value) -> RpcError {
+        RpcError { code: -32000, message: "boom".to_owned(), data: Some(data) }
+    }
+
+    #[test]
+    fn rpc_display_matches_error_rpc_display() {
+        let e = error_with_data(serde_json::json!(null));
+        let wrapped = Error::Rpc(e.clone());
+        assert_eq!(wrapped.to_string(), format!("RPC error response: {}", e));
+    }
+
+    #[test]
+    fn data_as_none_when_no_data() {
+        let e = RpcError { code: -32000, message: "boom".to_owned(), data: None };
+        assert!(e.data_as::<String>().is_none());
+    }
+
+    #[test]
+    fn data_as_deserializes_matching_shape() {
+        let e = error_with_data(serde_json::json!({"reason": "reverted"}));
+
+        #[derive(serde::Deserialize)]
+        struct Data {
+            reason: String,
+        }
+
+        let data: Data = e.data_as::<Data>().unwrap().unwrap();
+        assert_eq!(data.reason, "reverted");
+    }
+
+    #[test]
+    fn data_as_errs_on_shape_mismatch() {
+        let e = error_with_data(serde_json::json!("not an object"));
+
+        #[derive(serde::Deserialize)]
+        struct Data {
+            #[allow(dead_code)]
+            reason: String,
+        }
+
+        assert!(e.data_as::<Data>().unwrap().is_err());
+    }
+
+    #[test]
+    fn find_data_string_descends_into_nested_objects_and_arrays() {
+        let e = error_with_data(serde_json::json!({
+            "details": [
+                {"code": "not-it"},
+                {"revertReason": "insufficient funds"}
+            ]
+        }));
+
+        let found = e.find_data_string(|s| s == "insufficient funds");
+        assert_eq!(found, Some("insufficient funds"));
+    }
+
+    #[test]
+    fn find_data_string_none_when_no_leaf_matches() {
+        let e = error_with_data(serde_json::json!({"reason": "reverted"}));
+        assert_eq!(e.find_data_string(|s| s == "nope"), None);
+    }
+}
@@ -0,0 +1,221 @@
+// Rust JSON-RPC Library
+// Written in 2015 by
+//     Andrew Poelstra <apoelstra@wpsoftware.net>
+//
+// Forked in 2019 by
+//   Harry Barber <harrybarber@protonmail.com>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the CC0 Public Domain Dedication
+// along with this software.
+// If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
+//
+
+//! # IPC client support
+//!
+//! Local nodes (e.g. a `geth.ipc` socket) often expose the same JSONRPC
+//! methods as the HTTP API over a Unix domain socket (a named pipe on
+//! Windows) instead. [`IpcClient`] talks to one of those: like
+//! [`crate::ws::WsClient`] it keeps a single connection open and matches
+//! responses back to callers by id, but unlike a WebSocket the stream isn't
+//! already framed into messages, so the background reader has to find the
+//! JSON value boundaries itself.
+//!
+
+use std::sync::Arc;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt, ReadHalf, WriteHalf};
+use tokio::sync::Mutex as AsyncMutex;
+
+use crate::transport::PendingRequests;
+use crate::util::split_json_values;
+use crate::{error::Error, Request, Response};
+
+#[cfg(unix)]
+type IpcStream = tokio::net::UnixStream;
+#[cfg(windows)]
+type IpcStream = tokio::net::windows::named_pipe::NamedPipeClient;
+
+#[cfg(unix)]
+async fn connect(path: &str) -> Result<IpcStream, Error> {
+    Ok(tokio::net::UnixStream::connect(path).await?)
+}
+
+#[cfg(windows)]
+async fn connect(path: &str) -> Result<IpcStream, Error> {
+    Ok(tokio::net::windows::named_pipe::ClientOptions::new().open(path)?)
+}
+
+struct Inner {
+    write: AsyncMutex<WriteHalf<IpcStream>>,
+    pending: PendingRequests,
+}
+
+/// A handle to a persistent JSONRPC connection over a Unix domain socket (or,
+/// on Windows, a named pipe).
+///
+/// Cloning an `IpcClient` is cheap; every clone shares the same background
+/// dispatch task and connection.
+#[derive(Clone)]
+pub struct IpcClient {
+    inner: Arc<Inner>,
+}
+
+impl IpcClient {
+    /// Connects to the socket (or pipe) at `path` and spawns the background
+    /// dispatch loop.
+    pub async fn connect(path: &str) -> Result<IpcClient, Error> {
+        let stream = connect(path).await?;
+        let (read, write) = tokio::io::split(stream);
+
+        let inner = Arc::new(Inner {
+            write: AsyncMutex::new(write),
+            pending: PendingRequests::new(),
+        });
+
+        tokio::spawn(Self::dispatch_loop(inner.clone(), read));
+
+        Ok(IpcClient { inner })
+    }
+
+    /// Builds and sends a request, then deserializes the result.
+    pub async fn do_rpc<T: for<'a> serde::de::Deserialize<'a>>(
+        &self,
+        rpc_name: &str,
+        args: &[serde_json::Value],
+    ) -> Result<T, Error> {
+        let request = self.build_request(rpc_name, args);
+        let response = self.send_request(&request).await?;
+        response.into_result()
+    }
+
+    /// Sends a request over the socket and awaits the matching response.
+    pub async fn send_request(&self, request: &Request<'_, '_>) -> Result<Response, Error> {
+        let rx = self.inner.pending.register(&request.id);
+
+        let body = serde_json::to_vec(request)?;
+        if let Err(e) = self.write(&body).await {
+            self.inner.pending.cancel(&request.id);
+            return Err(e);
+        }
+
+        rx.await.map_err(|_| Error::ConnectionClosed)
+    }
+
+    /// Sends a batch of requests. The return vector holds the response for
+    /// the request at the corresponding index. Waits until every request is
+    /// answered or the connection closes; a request still unanswered when
+    /// the connection closes gets [`None`] in its slot.
+    pub async fn send_batch(
+        &self,
+        requests: &[Request<'_, '_>],
+    ) -> Result<Vec<Option<Response>>, Error> {
+        if requests.is_empty() {
+            return Err(Error::EmptyBatch);
+        }
+
+        let receivers: Vec<_> = requests
+            .iter()
+            .map(|r| self.inner.pending.register(&r.id))
+            .collect();
+
+        let body = serde_json::to_vec(requests)?;
+        if let Err(e) = self.write(&body).await {
+            for r in requests {
+                self.inner.pending.cancel(&r.id);
+            }
+            return Err(e);
+        }
+
+        let mut results = Vec::with_capacity(receivers.len());
+        for rx in receivers {
+            results.push(rx.await.ok());
+        }
+        Ok(results)
+    }
+
+    /// Builds a request with a fresh, client-assigned id.
+    pub fn build_request<'a, 'b>(
+        &self,
+        name: &'a str,
+        params: &'b [serde_json::Value],
+    ) -> Request<'a, 'b> {
+        self.inner.pending.build_request(name, params)
+    }
+
+    async fn write(&self, body: &[u8]) -> Result<(), Error> {
+        let mut write = self.inner.write.lock().await;
+        write.write_all(body).await?;
+        write.flush().await?;
+        Ok(())
+    }
+
+    async fn dispatch_loop(inner: Arc<Inner>, mut read: ReadHalf<IpcStream>) {
+        let mut buf = Vec::new();
+        let mut chunk = [0u8; 4096];
+
+        loop {
+            let n = match read.read(&mut chunk).await {
+                Ok(0) | Err(_) => break,
+                Ok(n) => n,
+            };
+            buf.extend_from_slice(&chunk[..n]);
+
+            for frame in split_json_values(&mut buf) {
+                Self::dispatch_frame(&inner, &frame);
+            }
+        }
+
+        inner.pending.clear();
+    }
+
+    fn dispatch_frame(inner: &Inner, frame: &[u8]) {
+        for response in parse_responses(frame) {
+            inner.pending.complete(response);
+        }
+    }
+}
+
+/// Parses one complete JSON frame (as produced by [`split_json_values`])
+/// into the [`Response`]s it contains, whether it's a single response or a
+/// batch. Malformed frames, or array elements that aren't responses, are
+/// silently dropped rather than failing the whole frame.
+fn parse_responses(frame: &[u8]) -> Vec<Response> {
+    match serde_json::from_slice::<serde_json::Value>(frame) {
+        Ok(serde_json::Value::Array(values)) => values
+            .into_iter()
+            .filter_map(|v| serde_json::from_value(v).ok())
+            .collect(),
+        Ok(value) => serde_json::from_value(value).ok().into_iter().collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_single_response_frame() {
+        let frame = br#"{"jsonrpc":"2.0","result":7,"id":"1"}"#;
+        let responses = parse_responses(frame);
+        assert_eq!(responses.len(), 1);
+        assert_eq!(responses[0].id, serde_json::json!("1"));
+    }
+
+    #[test]
+    fn parses_batch_frame() {
+        let frame = br#"[{"jsonrpc":"2.0","result":7,"id":"1"},{"jsonrpc":"2.0","result":9,"id":"2"}]"#;
+        let responses = parse_responses(frame);
+        assert_eq!(responses.len(), 2);
+    }
+
+    #[test]
+    fn drops_malformed_frame() {
+        assert!(parse_responses(b"not json").is_empty());
+    }
+}
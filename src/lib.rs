@@ -25,10 +25,17 @@ extern crate serde;
 
 pub mod client;
 pub mod error;
+pub mod ipc;
+pub mod server;
+mod transport;
 mod util;
+pub mod ws;
 
-pub use client::Client;
+pub use client::{Client, ClientBuilder};
 pub use error::Error;
+pub use ipc::IpcClient;
+pub use server::Server;
+pub use ws::WsClient;
 
 #[derive(Debug, Clone, PartialEq, Serialize)]
 /// Represents the JSONRPC request object.
@@ -41,10 +48,16 @@ pub struct Request<'a, 'b> {
 
 #[derive(Debug, Clone, PartialEq, Deserialize)]
 /// Represents the JSONRPC response object.
+///
+/// `jsonrpc` and `id` are relaxed relative to the 2.0 spec: plenty of real
+/// servers omit `jsonrpc` entirely, or reply with string/number/null `id`s
+/// in ways the spec doesn't strictly sanction, and rejecting those replies
+/// outright is less useful than just accepting them.
 pub struct Response {
     pub result: Option<serde_json::Value>,
     pub error: Option<error::RpcError>,
     pub id: serde_json::Value,
+    #[serde(default)]
     pub jsonrpc: Option<String>,
 }
 
@@ -84,10 +97,67 @@ impl Response {
     }
 }
 
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+/// A server-initiated push that isn't a response to anything we sent, and so
+/// has no `id` — e.g. a WebSocket/IPC subscription notification.
+pub struct Notification {
+    pub method: String,
+    pub params: serde_json::Value,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// Either a single [`Response`] or a batch of them.
+///
+/// A conforming server replies to a batch request with a JSON array, but
+/// several real-world servers collapse a one-element batch down to a bare
+/// response object. Parsing into `Message` instead of `Vec<Response>`
+/// tolerates either shape.
+pub enum Message {
+    /// A single response.
+    Single(Response),
+    /// A batch of responses.
+    Batch(Vec<Response>),
+}
+
+impl<'de> serde::de::Deserialize<'de> for Message {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::de::Deserializer<'de>,
+    {
+        struct MessageVisitor;
+
+        impl<'de> serde::de::Visitor<'de> for MessageVisitor {
+            type Value = Message;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("a JSONRPC response object or an array of them")
+            }
+
+            fn visit_seq<A>(self, seq: A) -> Result<Self::Value, A::Error>
+            where
+                A: serde::de::SeqAccess<'de>,
+            {
+                let batch = Vec::deserialize(serde::de::value::SeqAccessDeserializer::new(seq))?;
+                Ok(Message::Batch(batch))
+            }
+
+            fn visit_map<A>(self, map: A) -> Result<Self::Value, A::Error>
+            where
+                A: serde::de::MapAccess<'de>,
+            {
+                let single = Response::deserialize(serde::de::value::MapAccessDeserializer::new(map))?;
+                Ok(Message::Single(single))
+            }
+        }
+
+        deserializer.deserialize_any(MessageVisitor)
+    }
+}
+
 #[cfg(test)]
 mod tests {
 
-    use super::Response;
+    use super::{Message, Response};
     use serde_json;
 
     #[test]
@@ -154,4 +224,24 @@ mod tests {
         let batch_response: Vec<Response> = serde_json::from_str(&s).unwrap();
         assert_eq!(batch_response.len(), 5);
     }
+
+    #[test]
+    fn message_parses_batch() {
+        let s = r#"[{"jsonrpc": "2.0", "result": 7, "id": "1"}]"#;
+        match serde_json::from_str::<Message>(s).unwrap() {
+            Message::Batch(responses) => assert_eq!(responses.len(), 1),
+            Message::Single(_) => panic!("expected a batch"),
+        }
+    }
+
+    #[test]
+    fn message_parses_collapsed_single_element_batch() {
+        // Some servers reply to a one-element batch request with a bare
+        // object instead of a one-element array.
+        let s = r#"{"jsonrpc": "2.0", "result": 7, "id": "1"}"#;
+        match serde_json::from_str::<Message>(s).unwrap() {
+            Message::Single(response) => assert_eq!(response.id, serde_json::json!("1")),
+            Message::Batch(_) => panic!("expected a single response"),
+        }
+    }
 }
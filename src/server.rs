@@ -0,0 +1,326 @@
+// Rust JSON-RPC Library
+// Written in 2015 by
+//     Andrew Poelstra <apoelstra@wpsoftware.net>
+//
+// Forked in 2019 by
+//   Harry Barber <harrybarber@protonmail.com>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the CC0 Public Domain Dedication
+// along with this software.
+// If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
+//
+
+//! # Server support
+//!
+//! Everything else in this crate is for talking to a JSONRPC server; this
+//! module is for being one. [`Server`] is a method-dispatch registry built
+//! on the same [`crate::Request`]/[`Response`] wire types the clients use,
+//! plus a thin `hyper` adapter for serving it over HTTP.
+//!
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request as HttpRequest, Response as HttpResponse, StatusCode};
+use serde::Deserialize;
+
+use crate::{error::Error, error::RpcError, Response};
+
+/// The result of a handler invocation: the same result shape the registered
+/// method returns, boxed so `Server` can store handlers for arbitrarily
+/// different async method bodies in one map.
+pub type HandlerFuture = Pin<Box<dyn Future<Output = Result<serde_json::Value, RpcError>> + Send>>;
+
+/// A registered method implementation.
+pub type Handler = Box<dyn Fn(&[serde_json::Value]) -> HandlerFuture + Send + Sync>;
+
+/// An owned JSONRPC request, as received by a [`Server`].
+///
+/// [`crate::Request`] borrows its `method` and `params` so client code can
+/// build one without allocating on every call; a server has no caller to
+/// borrow from, so it needs an owned counterpart to deserialize into.
+///
+/// `id` is `Option<Option<Value>>`, not `Option<Value>`: serde's blanket
+/// `Option<T>` impl treats a JSON `null` the same as the key being absent
+/// altogether, which would make `"id": null` indistinguishable from a
+/// notification. The outer `Option` says whether the key was present at
+/// all (`None` = absent = notification); the inner one is the id itself,
+/// which the spec allows to genuinely be `null`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct IncomingRequest {
+    pub method: String,
+    #[serde(default)]
+    pub params: Vec<serde_json::Value>,
+    #[serde(default, deserialize_with = "deserialize_present_field")]
+    pub id: Option<Option<serde_json::Value>>,
+    #[serde(default)]
+    pub jsonrpc: Option<String>,
+}
+
+/// Wraps an ordinary `Option<T>::deserialize` so the caller can tell "field
+/// present" (`Some`, regardless of whether the value itself was `null`)
+/// apart from "field absent" (only reachable via `#[serde(default)]`, since
+/// this function is never invoked for a missing key).
+fn deserialize_present_field<'de, D>(
+    deserializer: D,
+) -> Result<Option<Option<serde_json::Value>>, D::Error>
+where
+    D: serde::de::Deserializer<'de>,
+{
+    Option::deserialize(deserializer).map(Some)
+}
+
+/// A JSONRPC 2.0 method-dispatch server.
+///
+/// Register methods with [`Server::register`], then feed request bodies to
+/// [`Server::handle`]. `Server` doesn't own a transport itself; pass it to
+/// [`serve`] to run it over HTTP, or call `handle` directly from your own
+/// WebSocket/IPC connection handling.
+#[derive(Default)]
+pub struct Server {
+    methods: HashMap<String, Handler>,
+}
+
+impl Server {
+    /// Creates an empty server.
+    pub fn new() -> Self {
+        Server { methods: HashMap::new() }
+    }
+
+    /// Registers a method implementation, replacing any previous handler
+    /// for the same name.
+    pub fn register<F>(mut self, method: impl Into<String>, handler: F) -> Self
+    where
+        F: Fn(&[serde_json::Value]) -> HandlerFuture + Send + Sync + 'static,
+    {
+        self.methods.insert(method.into(), Box::new(handler));
+        self
+    }
+
+    /// Parses `body` as a single request or a batch, dispatches each call,
+    /// and serializes the response(s) back to bytes.
+    ///
+    /// Returns an empty body for a lone notification, since the spec
+    /// forbids a response to one.
+    pub async fn handle(&self, body: &[u8]) -> Vec<u8> {
+        let value: serde_json::Value = match serde_json::from_slice(body) {
+            Ok(v) => v,
+            Err(_) => {
+                return Self::to_bytes(&Self::error_response(
+                    serde_json::Value::Null,
+                    RpcError::parse_error(),
+                ))
+            }
+        };
+
+        match value {
+            serde_json::Value::Array(items) if !items.is_empty() => {
+                let mut responses = Vec::with_capacity(items.len());
+                for item in items {
+                    if let Some(response) = self.dispatch_value(item).await {
+                        responses.push(response);
+                    }
+                }
+                // A batch made up entirely of notifications gets no
+                // response at all, per spec ("the server MUST NOT return
+                // an empty Array") — not a serialized `[]`.
+                if responses.is_empty() {
+                    Vec::new()
+                } else {
+                    Self::to_bytes(&responses)
+                }
+            }
+            serde_json::Value::Array(_) => {
+                Self::to_bytes(&Self::error_response(serde_json::Value::Null, RpcError::invalid_request()))
+            }
+            other => match self.dispatch_value(other).await {
+                Some(response) => Self::to_bytes(&response),
+                None => Vec::new(),
+            },
+        }
+    }
+
+    /// Dispatches a single already-parsed request value. Returns `None` for
+    /// a notification (valid request with no `id`), which gets no response.
+    async fn dispatch_value(&self, value: serde_json::Value) -> Option<Response> {
+        let request: IncomingRequest = match serde_json::from_value(value) {
+            Ok(r) => r,
+            Err(_) => return Some(Self::error_response(serde_json::Value::Null, RpcError::invalid_request())),
+        };
+
+        let id = match request.id.clone() {
+            // The `id` key was present (possibly `null`, which the spec
+            // allows and which we must still echo back).
+            Some(id) => id.unwrap_or(serde_json::Value::Null),
+            // The `id` key was absent entirely: a notification.
+            None => {
+                // Notification: run it for effect, but nothing is waiting on a reply.
+                let _ = self.dispatch(request).await;
+                return None;
+            }
+        };
+
+        Some(match self.dispatch(request).await {
+            Ok(result) => Response { result: Some(result), error: None, id, jsonrpc: Some("2.0".to_owned()) },
+            Err(e) => Self::error_response(id, e),
+        })
+    }
+
+    async fn dispatch(&self, request: IncomingRequest) -> Result<serde_json::Value, RpcError> {
+        match self.methods.get(&request.method) {
+            Some(handler) => handler(&request.params).await,
+            None => Err(RpcError::method_not_found()),
+        }
+    }
+
+    fn error_response(id: serde_json::Value, error: RpcError) -> Response {
+        Response { result: None, error: Some(error), id, jsonrpc: Some("2.0".to_owned()) }
+    }
+
+    fn to_bytes<T: serde::Serialize>(value: &T) -> Vec<u8> {
+        serde_json::to_vec(value).expect("JSONRPC responses are always serializable")
+    }
+}
+
+// `Response` only derives `Deserialize`, since clients never send one; the
+// server needs to write it, so it is serialized here. `result` and `error`
+// are left out when `None`.
+impl serde::Serialize for Response {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeMap;
+        let mut map = serializer.serialize_map(None)?;
+        map.serialize_entry("jsonrpc", &self.jsonrpc)?;
+        if let Some(result) = &self.result {
+            map.serialize_entry("result", result)?;
+        }
+        if let Some(error) = &self.error {
+            map.serialize_entry("error", error)?;
+        }
+        map.serialize_entry("id", &self.id)?;
+        map.end()
+    }
+}
+
+/// Serves `server` over HTTP at `addr`, POSTing every request body to
+/// [`Server::handle`], whatever its path. Any other method gets a `404`.
+pub async fn serve(server: Arc<Server>, addr: SocketAddr) -> Result<(), Error> {
+    let make_service = make_service_fn(move |_conn| {
+        let server = server.clone();
+        async move {
+            Ok::<_, hyper::Error>(service_fn(move |req| {
+                let server = server.clone();
+                async move { Ok::<_, hyper::Error>(handle_http(&server, req).await) }
+            }))
+        }
+    });
+
+    hyper::Server::bind(&addr).serve(make_service).await?;
+    Ok(())
+}
+
+async fn handle_http(server: &Server, req: HttpRequest<Body>) -> HttpResponse<Body> {
+    if req.method() != Method::POST {
+        return HttpResponse::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(Body::empty())
+            .expect("static response is well-formed");
+    }
+
+    let body = match hyper::body::to_bytes(req.into_body()).await {
+        Ok(b) => b,
+        Err(_) => {
+            return HttpResponse::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .body(Body::empty())
+                .expect("static response is well-formed")
+        }
+    };
+
+    let response_body = server.handle(&body).await;
+    HttpResponse::builder()
+        .status(StatusCode::OK)
+        .header(hyper::header::CONTENT_TYPE, "application/json")
+        .body(Body::from(response_body))
+        .expect("static response is well-formed")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn echo_server() -> Server {
+        Server::new().register("echo", |params| {
+            let params = params.to_vec();
+            Box::pin(async move { Ok(serde_json::Value::Array(params)) })
+        })
+    }
+
+    #[tokio::test]
+    async fn dispatches_a_call_and_echoes_the_id() {
+        let server = echo_server();
+        let body = br#"{"jsonrpc":"2.0","method":"echo","params":[1,2],"id":7}"#;
+        let response: Response = serde_json::from_slice(&server.handle(body).await).unwrap();
+        assert_eq!(response.id, serde_json::json!(7));
+        assert_eq!(response.result, Some(serde_json::json!([1, 2])));
+    }
+
+    #[tokio::test]
+    async fn unknown_method_is_method_not_found() {
+        let server = echo_server();
+        let body = br#"{"jsonrpc":"2.0","method":"nope","params":[],"id":1}"#;
+        let response: Response = serde_json::from_slice(&server.handle(body).await).unwrap();
+        assert_eq!(response.error.unwrap().code, -32601);
+    }
+
+    #[tokio::test]
+    async fn invalid_json_is_parse_error_with_null_id() {
+        let server = echo_server();
+        let response: Response = serde_json::from_slice(&server.handle(b"not json").await).unwrap();
+        assert_eq!(response.id, serde_json::Value::Null);
+        assert_eq!(response.error.unwrap().code, -32700);
+    }
+
+    #[tokio::test]
+    async fn notification_without_id_gets_no_response() {
+        let server = echo_server();
+        let body = br#"{"jsonrpc":"2.0","method":"echo","params":[]}"#;
+        assert!(server.handle(body).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn explicit_null_id_is_not_a_notification() {
+        let server = echo_server();
+        let body = br#"{"jsonrpc":"2.0","method":"echo","params":[],"id":null}"#;
+        let bytes = server.handle(body).await;
+        assert!(!bytes.is_empty());
+        let response: Response = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(response.id, serde_json::Value::Null);
+    }
+
+    #[tokio::test]
+    async fn batch_of_only_notifications_gets_no_response() {
+        let server = echo_server();
+        let body = br#"[{"jsonrpc":"2.0","method":"echo","params":[]},
+                         {"jsonrpc":"2.0","method":"echo","params":[]}]"#;
+        assert!(server.handle(body).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn batch_mixing_calls_and_notifications_only_replies_to_calls() {
+        let server = echo_server();
+        let body = br#"[{"jsonrpc":"2.0","method":"echo","params":[],"id":1},
+                         {"jsonrpc":"2.0","method":"echo","params":[]}]"#;
+        let responses: Vec<Response> = serde_json::from_slice(&server.handle(body).await).unwrap();
+        assert_eq!(responses.len(), 1);
+        assert_eq!(responses[0].id, serde_json::json!(1));
+    }
+}
@@ -0,0 +1,171 @@
+// Rust JSON-RPC Library
+// Written in 2015 by
+//     Andrew Poelstra <apoelstra@wpsoftware.net>
+//
+// Forked in 2019 by
+//   Harry Barber <harrybarber@protonmail.com>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the CC0 Public Domain Dedication
+// along with this software.
+// If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
+//
+
+//! # Shared long-lived transport plumbing
+//!
+//! [`crate::ws`] and [`crate::ipc`] both keep a single connection open and
+//! match inbound responses back to the caller that sent the matching
+//! request, instead of getting the response back from the same round trip
+//! like the HTTP [`crate::client::Client`] does. [`PendingRequests`] is the
+//! id-keyed bookkeeping both of them share.
+//!
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use tokio::sync::oneshot;
+
+use crate::util::HashableValue;
+use crate::{Request, Response};
+
+/// Tracks in-flight requests on a long-lived connection, keyed by request
+/// `id`, so a background read loop can route each inbound [`Response`] back
+/// to the caller awaiting it.
+pub(crate) struct PendingRequests {
+    nonce: AtomicU64,
+    pending: Mutex<HashMap<HashableValue<'static>, oneshot::Sender<Response>>>,
+}
+
+impl PendingRequests {
+    pub(crate) fn new() -> Self {
+        PendingRequests {
+            nonce: AtomicU64::new(0),
+            pending: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Builds a request with a fresh, client-assigned id.
+    pub(crate) fn build_request<'a, 'b>(
+        &self,
+        name: &'a str,
+        params: &'b [serde_json::Value],
+    ) -> Request<'a, 'b> {
+        let nonce = self.nonce.fetch_add(1, Ordering::SeqCst) + 1;
+        Request {
+            method: name,
+            params,
+            id: From::from(nonce),
+            jsonrpc: Some("2.0"),
+        }
+    }
+
+    /// Registers interest in the response to `id`, returning the receiving
+    /// end of the oneshot it will be delivered on.
+    pub(crate) fn register(&self, id: &serde_json::Value) -> oneshot::Receiver<Response> {
+        let (tx, rx) = oneshot::channel();
+        self.pending
+            .lock()
+            .unwrap()
+            .insert(HashableValue::owned(id.clone()), tx);
+        rx
+    }
+
+    /// Cancels a registration, e.g. because sending the request failed.
+    pub(crate) fn cancel(&self, id: &serde_json::Value) {
+        self.pending
+            .lock()
+            .unwrap()
+            .remove(&HashableValue::owned(id.clone()));
+    }
+
+    /// Routes an inbound response to whichever caller registered its id, if
+    /// any (a response with no matching registration is dropped).
+    pub(crate) fn complete(&self, response: Response) {
+        let key = HashableValue::owned(response.id.clone());
+        if let Some(tx) = self.pending.lock().unwrap().remove(&key) {
+            let _ = tx.send(response);
+        }
+    }
+
+    /// Drops every pending registration, waking each caller's receiver with
+    /// a `RecvError` (which callers map to [`crate::Error::ConnectionClosed`]).
+    pub(crate) fn clear(&self) {
+        self.pending.lock().unwrap().clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn build_request_assigns_increasing_ids() {
+        let pending = PendingRequests::new();
+        let first = pending.build_request("foo", &[]);
+        let second = pending.build_request("foo", &[]);
+        assert!(second.id.as_u64().unwrap() > first.id.as_u64().unwrap());
+    }
+
+    #[tokio::test]
+    async fn complete_delivers_to_the_registered_receiver() {
+        let pending = PendingRequests::new();
+        let request = pending.build_request("foo", &[]);
+        let rx = pending.register(&request.id);
+
+        let response = Response {
+            result: Some(serde_json::json!(42)),
+            error: None,
+            id: request.id.clone(),
+            jsonrpc: Some("2.0".to_owned()),
+        };
+        pending.complete(response);
+
+        let received = rx.await.unwrap();
+        assert_eq!(received.result, Some(serde_json::json!(42)));
+    }
+
+    #[tokio::test]
+    async fn complete_with_unknown_id_is_dropped() {
+        let pending = PendingRequests::new();
+        let response = Response {
+            result: None,
+            error: None,
+            id: serde_json::json!("unregistered"),
+            jsonrpc: None,
+        };
+        // Should not panic; there's simply nobody listening.
+        pending.complete(response);
+    }
+
+    #[tokio::test]
+    async fn cancel_prevents_later_completion_from_being_observed() {
+        let pending = PendingRequests::new();
+        let request = pending.build_request("foo", &[]);
+        let rx = pending.register(&request.id);
+        pending.cancel(&request.id);
+
+        let response = Response {
+            result: Some(serde_json::json!(1)),
+            error: None,
+            id: request.id,
+            jsonrpc: None,
+        };
+        pending.complete(response);
+
+        assert!(rx.await.is_err());
+    }
+
+    #[tokio::test]
+    async fn clear_fails_every_pending_receiver() {
+        let pending = PendingRequests::new();
+        let request = pending.build_request("foo", &[]);
+        let rx = pending.register(&request.id);
+        pending.clear();
+        assert!(rx.await.is_err());
+    }
+}
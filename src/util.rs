@@ -0,0 +1,182 @@
+// Rust JSON-RPC Library
+// Written in 2015 by
+//     Andrew Poelstra <apoelstra@wpsoftware.net>
+//
+// Forked in 2019 by
+//   Harry Barber <harrybarber@protonmail.com>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the CC0 Public Domain Dedication
+// along with this software.
+// If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
+//
+
+//! # Utilities
+//!
+//! Small helpers shared between the transports.
+//!
+
+use std::borrow::Cow;
+use std::hash::{Hash, Hasher};
+
+/// Wraps a `serde_json::Value` so it can be used as a `HashMap`/`HashSet` key.
+///
+/// JSONRPC request and subscription IDs are only ever strings, numbers or
+/// null, so we only need to hash those variants meaningfully; any other
+/// shape simply hashes to a fixed discriminant. The value is kept as a
+/// [`Cow`] so short-lived code can borrow a key out of a request it already
+/// owns, while long-lived code (background dispatch loops that outlive the
+/// request) can store an owned copy under the same type.
+#[derive(Debug, Clone)]
+pub struct HashableValue<'a>(pub Cow<'a, serde_json::Value>);
+
+impl<'a> HashableValue<'a> {
+    /// Borrows a key out of a `serde_json::Value` that outlives the map lookup.
+    pub fn borrowed(value: &'a serde_json::Value) -> Self {
+        HashableValue(Cow::Borrowed(value))
+    }
+
+    /// Takes ownership of a `serde_json::Value`, for keys stored past the
+    /// lifetime of the request that produced them.
+    pub fn owned(value: serde_json::Value) -> HashableValue<'static> {
+        HashableValue(Cow::Owned(value))
+    }
+}
+
+impl<'a> PartialEq for HashableValue<'a> {
+    fn eq(&self, other: &HashableValue<'a>) -> bool {
+        self.0 == other.0
+    }
+}
+
+impl<'a> Eq for HashableValue<'a> {}
+
+/// Splits complete, top-level JSON values off the front of `buf`, returning
+/// each as its own byte vector and leaving any trailing partial value in
+/// `buf` for the next call. Only objects and arrays are recognised; bytes
+/// outside them (whitespace between values, bare scalars) are discarded.
+///
+/// IPC transports have no framing of their own (unlike HTTP's
+/// `Content-Length`), so a reader has to track brace/bracket nesting itself
+/// to know where one JSON value ends and the next begins.
+pub(crate) fn split_json_values(buf: &mut Vec<u8>) -> Vec<Vec<u8>> {
+    let mut values = Vec::new();
+    let mut depth: i32 = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+    let mut value_start: Option<usize> = None;
+
+    let mut i = 0;
+    while i < buf.len() {
+        let b = buf[i];
+        if in_string {
+            if escaped {
+                escaped = false;
+            } else if b == b'\\' {
+                escaped = true;
+            } else if b == b'"' {
+                in_string = false;
+            }
+        } else {
+            match b {
+                b'"' => in_string = true,
+                b'{' | b'[' => {
+                    if value_start.is_none() {
+                        value_start = Some(i);
+                    }
+                    depth += 1;
+                }
+                b'}' | b']' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        if let Some(start) = value_start.take() {
+                            values.push(buf[start..=i].to_vec());
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        i += 1;
+    }
+
+    let consumed = value_start.unwrap_or(buf.len());
+    buf.drain(..consumed);
+    values
+}
+
+impl<'a> Hash for HashableValue<'a> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        match self.0.as_ref() {
+            serde_json::Value::Null => 0u8.hash(state),
+            serde_json::Value::Bool(b) => {
+                1u8.hash(state);
+                b.hash(state);
+            }
+            serde_json::Value::Number(n) => {
+                2u8.hash(state);
+                n.to_string().hash(state);
+            }
+            serde_json::Value::String(s) => {
+                3u8.hash(state);
+                s.hash(state);
+            }
+            serde_json::Value::Array(_) => 4u8.hash(state),
+            serde_json::Value::Object(_) => 5u8.hash(state),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_two_concatenated_values() {
+        let mut buf = br#"{"a":1}{"b":2}"#.to_vec();
+        let values = split_json_values(&mut buf);
+        assert_eq!(values, vec![br#"{"a":1}"#.to_vec(), br#"{"b":2}"#.to_vec()]);
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn keeps_a_trailing_partial_value_for_next_time() {
+        let mut buf = br#"{"a":1}{"b":"#.to_vec();
+        let values = split_json_values(&mut buf);
+        assert_eq!(values, vec![br#"{"a":1}"#.to_vec()]);
+        assert_eq!(buf, br#"{"b":"#.to_vec());
+
+        buf.extend_from_slice(b"2}");
+        let values = split_json_values(&mut buf);
+        assert_eq!(values, vec![br#"{"b":2}"#.to_vec()]);
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn ignores_braces_inside_strings() {
+        let original = br#"{"a":"{}[","b":1}"#.to_vec();
+        let mut buf = original.clone();
+        let values = split_json_values(&mut buf);
+        assert_eq!(values, vec![original]);
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn handles_escaped_quotes_inside_strings() {
+        let mut buf = br#"{"a":"\"}\""}"#.to_vec();
+        let values = split_json_values(&mut buf);
+        assert_eq!(values.len(), 1);
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn hashable_value_distinguishes_types_with_the_same_string_form() {
+        let number = serde_json::json!(1);
+        let string = serde_json::json!("1");
+        assert_ne!(HashableValue::borrowed(&number), HashableValue::borrowed(&string));
+    }
+}
@@ -0,0 +1,439 @@
+// Rust JSON-RPC Library
+// Written in 2015 by
+//     Andrew Poelstra <apoelstra@wpsoftware.net>
+//
+// Forked in 2019 by
+//   Harry Barber <harrybarber@protonmail.com>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the CC0 Public Domain Dedication
+// along with this software.
+// If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
+//
+
+//! # WebSocket client support
+//!
+//! Unlike the HTTP [`crate::client::Client`], a WebSocket connection is
+//! long-lived: requests and responses are multiplexed over a single socket,
+//! and the server may push notifications that aren't a response to anything
+//! we sent. [`WsClient`] owns a background task that reads the socket,
+//! matches responses back to callers by `id`, and routes subscription
+//! notifications into streams.
+//!
+
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context as TaskContext, Poll};
+use std::time::Duration;
+
+use futures_util::stream::{SplitSink, SplitStream, Stream};
+use futures_util::{SinkExt, StreamExt};
+use tokio::net::TcpStream;
+use tokio::sync::{mpsc, Mutex as AsyncMutex};
+use tokio::time::Instant;
+use tokio_tungstenite::tungstenite::Message as WsMessage;
+use tokio_tungstenite::{connect_async, MaybeTlsStream, WebSocketStream};
+
+use crate::transport::PendingRequests;
+use crate::{error::Error, util::HashableValue, Request, Response};
+
+type WsStream = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// The identifier a server assigns to a subscription, returned as the
+/// `result` of the `*_subscribe` call that created it.
+pub type SubscriptionId = serde_json::Value;
+
+/// Keepalive configuration for a [`WsClient`]'s background connection.
+///
+/// A WebSocket that has died (peer gone, proxy timed out, network partition)
+/// doesn't necessarily tell us so; without pings we'd otherwise only find
+/// out the next time we tried to send a request.
+#[derive(Debug, Clone, Copy)]
+pub struct PingConfig {
+    /// How often to send a ping frame.
+    pub ping_interval: Duration,
+    /// Close the connection if no frame at all (ping, pong, response,
+    /// notification) has arrived for this long.
+    pub inactive_limit: Duration,
+    /// Close the connection after this many consecutive pings for which no
+    /// frame arrived before the next ping was due.
+    pub max_failures: u32,
+}
+
+impl Default for PingConfig {
+    fn default() -> Self {
+        let ping_interval = Duration::from_secs(30);
+        let max_failures = 3;
+        PingConfig {
+            ping_interval,
+            // Must outlast `max_failures` consecutive missed pings, or the
+            // inactive-limit check fires first and `max_failures` is never
+            // reachable.
+            inactive_limit: ping_interval * (max_failures + 1),
+            max_failures,
+        }
+    }
+}
+
+struct Inner {
+    write: AsyncMutex<SplitSink<WsStream, WsMessage>>,
+    pending: PendingRequests,
+    subscriptions: Mutex<HashMap<HashableValue<'static>, mpsc::UnboundedSender<serde_json::Value>>>,
+    last_seen: Mutex<Instant>,
+}
+
+impl Inner {
+    fn touch(&self) {
+        *self.last_seen.lock().unwrap() = Instant::now();
+    }
+
+    /// Drops every pending request (waking their callers with a
+    /// [`Error::ConnectionClosed`]) and every subscription sender (ending
+    /// their streams with `None` instead of leaving them pending forever).
+    fn clear(&self) {
+        self.pending.clear();
+        self.subscriptions.lock().unwrap().clear();
+    }
+
+    /// Clears all in-flight state and tries to send a close frame.
+    async fn close(&self) {
+        self.clear();
+        let mut write = self.write.lock().await;
+        let _ = write.send(WsMessage::Close(None)).await;
+    }
+}
+
+/// A handle to a persistent JSONRPC WebSocket connection.
+///
+/// Cloning a `WsClient` is cheap; every clone shares the same background
+/// dispatch task and connection.
+#[derive(Clone)]
+pub struct WsClient {
+    inner: Arc<Inner>,
+}
+
+impl WsClient {
+    /// Connects to `url` and spawns the background dispatch loop (and, if
+    /// `ping` is given, a keepalive loop alongside it).
+    pub async fn connect(url: &str, ping: Option<PingConfig>) -> Result<WsClient, Error> {
+        let (stream, _) = connect_async(url).await?;
+        let (write, read) = stream.split();
+
+        let inner = Arc::new(Inner {
+            write: AsyncMutex::new(write),
+            pending: PendingRequests::new(),
+            subscriptions: Mutex::new(HashMap::new()),
+            last_seen: Mutex::new(Instant::now()),
+        });
+
+        tokio::spawn(Self::dispatch_loop(inner.clone(), read));
+        if let Some(ping) = ping {
+            tokio::spawn(Self::ping_loop(inner.clone(), ping));
+        }
+
+        Ok(WsClient { inner })
+    }
+
+    /// Builds and sends a request, then deserializes the result.
+    pub async fn do_rpc<T: for<'a> serde::de::Deserialize<'a>>(
+        &self,
+        rpc_name: &str,
+        args: &[serde_json::Value],
+    ) -> Result<T, Error> {
+        let request = self.build_request(rpc_name, args);
+        let response = self.send_request(&request).await?;
+        response.into_result()
+    }
+
+    /// Sends a request over the socket and awaits the matching response.
+    pub async fn send_request(&self, request: &Request<'_, '_>) -> Result<Response, Error> {
+        let rx = self.inner.pending.register(&request.id);
+
+        let body = serde_json::to_string(request)?;
+        if let Err(e) = self.write(WsMessage::Text(body)).await {
+            self.inner.pending.cancel(&request.id);
+            return Err(e);
+        }
+
+        rx.await.map_err(|_| Error::ConnectionClosed)
+    }
+
+    /// Builds a request with a fresh, client-assigned id.
+    pub fn build_request<'a, 'b>(
+        &self,
+        name: &'a str,
+        params: &'b [serde_json::Value],
+    ) -> Request<'a, 'b> {
+        self.inner.pending.build_request(name, params)
+    }
+
+    /// Subscribes to a pub/sub method. The returned [`SubscriptionStream`]
+    /// yields the `result` field of every notification addressed to this
+    /// subscription, and unsubscribes when dropped.
+    pub async fn subscribe(
+        &self,
+        method: &str,
+        params: &[serde_json::Value],
+    ) -> Result<(SubscriptionId, SubscriptionStream), Error> {
+        let request = self.build_request(method, params);
+        let response = self.send_request(&request).await?;
+        let id: SubscriptionId = response.into_result()?;
+
+        let (tx, rx) = mpsc::unbounded_channel();
+        self.inner
+            .subscriptions
+            .lock()
+            .unwrap()
+            .insert(HashableValue::owned(id.clone()), tx);
+
+        let stream = SubscriptionStream {
+            client: self.clone(),
+            id: id.clone(),
+            unsubscribe_method: unsubscribe_method_name(method),
+            rx,
+        };
+
+        Ok((id, stream))
+    }
+
+    /// Unsubscribes from a previously-created subscription.
+    pub async fn unsubscribe(&self, method: &str, id: SubscriptionId) -> Result<(), Error> {
+        self.inner
+            .subscriptions
+            .lock()
+            .unwrap()
+            .remove(&HashableValue::owned(id.clone()));
+        let request = self.build_request(method, std::slice::from_ref(&id));
+        self.send_request(&request).await?;
+        Ok(())
+    }
+
+    async fn write(&self, message: WsMessage) -> Result<(), Error> {
+        let mut write = self.inner.write.lock().await;
+        write.send(message).await.map_err(Error::from)
+    }
+
+    async fn dispatch_loop(inner: Arc<Inner>, mut read: SplitStream<WsStream>) {
+        while let Some(message) = read.next().await {
+            let message = match message {
+                Ok(m) => m,
+                Err(_) => break,
+            };
+            inner.touch();
+
+            let text = match message {
+                WsMessage::Text(t) => t,
+                WsMessage::Binary(b) => match String::from_utf8(b) {
+                    Ok(t) => t,
+                    Err(_) => continue,
+                },
+                WsMessage::Close(_) => break,
+                WsMessage::Ping(_) | WsMessage::Pong(_) => continue,
+                _ => continue,
+            };
+
+            let value: serde_json::Value = match serde_json::from_str(&text) {
+                Ok(v) => v,
+                Err(_) => continue,
+            };
+
+            match classify_inbound(value) {
+                Inbound::Notification { subscription, payload } => {
+                    let key = HashableValue::owned(subscription);
+                    if let Some(tx) = inner.subscriptions.lock().unwrap().get(&key) {
+                        let _ = tx.send(payload);
+                    }
+                }
+                Inbound::Response(response) => inner.pending.complete(response),
+                Inbound::Unrecognized => {}
+            }
+        }
+
+        inner.clear();
+    }
+
+    async fn ping_loop(inner: Arc<Inner>, config: PingConfig) {
+        let mut ticker = tokio::time::interval(config.ping_interval);
+        let mut consecutive_failures = 0u32;
+        loop {
+            ticker.tick().await;
+
+            let quiet_for = inner.last_seen.lock().unwrap().elapsed();
+            if quiet_for >= config.inactive_limit {
+                inner.close().await;
+                return;
+            }
+
+            if quiet_for >= config.ping_interval {
+                consecutive_failures += 1;
+            } else {
+                consecutive_failures = 0;
+            }
+            if consecutive_failures >= config.max_failures {
+                inner.close().await;
+                return;
+            }
+
+            let mut write = inner.write.lock().await;
+            if write.send(WsMessage::Ping(Vec::new())).await.is_err() {
+                drop(write);
+                inner.close().await;
+                return;
+            }
+        }
+    }
+}
+
+/// What an inbound WebSocket frame's parsed JSON turned out to be.
+enum Inbound {
+    /// A subscription push: `params.subscription` matched, with its
+    /// `params.result` payload (or `null` if absent).
+    Notification { subscription: serde_json::Value, payload: serde_json::Value },
+    /// A reply to a request we sent.
+    Response(Response),
+    /// Neither of the above (e.g. malformed, or a response missing `id`).
+    Unrecognized,
+}
+
+/// Classifies one already-deserialized inbound JSON value as a subscription
+/// notification, a response, or neither. Pulled out of the dispatch loop so
+/// the classification logic can be tested without a live socket.
+fn classify_inbound(value: serde_json::Value) -> Inbound {
+    if let Some(subscription) = value.pointer("/params/subscription") {
+        let subscription = subscription.clone();
+        let payload = value
+            .pointer("/params/result")
+            .cloned()
+            .unwrap_or(serde_json::Value::Null);
+        return Inbound::Notification { subscription, payload };
+    }
+
+    if value.get("id").is_some() {
+        if let Ok(response) = serde_json::from_value::<Response>(value) {
+            return Inbound::Response(response);
+        }
+    }
+
+    Inbound::Unrecognized
+}
+
+/// Turns e.g. `eth_subscribe` into `eth_unsubscribe`, following the
+/// `*_subscribe`/`*_unsubscribe` convention used by pub/sub JSONRPC servers.
+/// Falls back to the literal method name `unsubscribe` if `method` doesn't
+/// follow the convention.
+fn unsubscribe_method_name(method: &str) -> String {
+    match method.strip_suffix("_subscribe") {
+        Some(prefix) => format!("{}_unsubscribe", prefix),
+        None => "unsubscribe".to_owned(),
+    }
+}
+
+/// A stream of notifications pushed into a subscription created with
+/// [`WsClient::subscribe`].
+///
+/// Call [`SubscriptionStream::close`] to unsubscribe deterministically.
+/// Dropping the stream without closing it best-effort unsubscribes in the
+/// background (if dropped from within a Tokio runtime) but otherwise just
+/// leaks the subscription on the server rather than risking a panic:
+/// `tokio::spawn` panics when called outside a runtime context, which a
+/// `Drop` impl has no way to prevent or recover from.
+pub struct SubscriptionStream {
+    client: WsClient,
+    id: SubscriptionId,
+    unsubscribe_method: String,
+    rx: mpsc::UnboundedReceiver<serde_json::Value>,
+}
+
+impl SubscriptionStream {
+    /// Unsubscribes and stops the stream. Prefer this over letting the
+    /// stream drop when you're in a position to `await` it.
+    pub async fn close(mut self) -> Result<(), Error> {
+        self.rx.close();
+        self.client
+            .unsubscribe(&self.unsubscribe_method, self.id.clone())
+            .await
+    }
+}
+
+impl Stream for SubscriptionStream {
+    type Item = serde_json::Value;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.rx).poll_next(cx)
+    }
+}
+
+impl Drop for SubscriptionStream {
+    fn drop(&mut self) {
+        // Only safe to do in the background if a runtime is actually
+        // available to run it on; otherwise leak the subscription instead
+        // of risking a panic mid-drop.
+        if tokio::runtime::Handle::try_current().is_err() {
+            return;
+        }
+
+        let client = self.client.clone();
+        let method = self.unsubscribe_method.clone();
+        let id = self.id.clone();
+        tokio::spawn(async move {
+            let _ = client.unsubscribe(&method, id).await;
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unsubscribe_method_name_follows_convention() {
+        assert_eq!(unsubscribe_method_name("eth_subscribe"), "eth_unsubscribe");
+        assert_eq!(unsubscribe_method_name("shh_subscribe"), "shh_unsubscribe");
+        assert_eq!(unsubscribe_method_name("subscribeToFoo"), "unsubscribe");
+    }
+
+    #[test]
+    fn classifies_subscription_notification() {
+        let value = serde_json::json!({
+            "jsonrpc": "2.0",
+            "method": "eth_subscription",
+            "params": {"subscription": "0x1", "result": {"block": 1}},
+        });
+        match classify_inbound(value) {
+            Inbound::Notification { subscription, payload } => {
+                assert_eq!(subscription, serde_json::json!("0x1"));
+                assert_eq!(payload, serde_json::json!({"block": 1}));
+            }
+            _ => panic!("expected a notification"),
+        }
+    }
+
+    #[test]
+    fn classifies_response() {
+        let value = serde_json::json!({"jsonrpc": "2.0", "result": 7, "id": "1"});
+        match classify_inbound(value) {
+            Inbound::Response(response) => assert_eq!(response.id, serde_json::json!("1")),
+            _ => panic!("expected a response"),
+        }
+    }
+
+    #[test]
+    fn classifies_unrecognized() {
+        let value = serde_json::json!({"foo": "bar"});
+        assert!(matches!(classify_inbound(value), Inbound::Unrecognized));
+    }
+
+    #[test]
+    fn default_ping_config_lets_max_failures_be_reached() {
+        // `inactive_limit` must not fire before `max_failures` consecutive
+        // missed pings can accumulate, or the latter is unreachable.
+        let config = PingConfig::default();
+        let time_to_reach_max_failures = config.ping_interval * config.max_failures;
+        assert!(config.inactive_limit > time_to_reach_max_failures);
+    }
+}